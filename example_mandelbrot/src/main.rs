@@ -1,8 +1,11 @@
-extern crate crossbeam;
 extern crate image;
 extern crate num;
+extern crate rand;
+extern crate rayon;
 
 use num::Complex;
+use rand::Rng;
+use rayon::prelude::*;
 use std::str::FromStr;
 
 use crate::image::ImageEncoder;
@@ -10,18 +13,139 @@ use image::codecs::png::PngEncoder;
 use image::ColorType;
 use std::fs::File;
 
-fn escape_time(c: Complex<f64>, limit: u32) -> Option<u32> {
+/// Which escape-time recurrence to iterate.
+///
+/// Every variant shares the same escape test (`norm_sqr() > 4.0`); they
+/// only differ in how `z` is stepped from one iteration to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FractalKind {
+    /// `z = z^2 + c`
+    Mandelbrot,
+    /// `z = z^3 + c`
+    Multibrot3,
+    /// `z = (|re(z)| + i|im(z)|)^2 + c`
+    BurningShip,
+}
+
+impl FromStr for FractalKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mandelbrot" => Ok(FractalKind::Mandelbrot),
+            "mandelbrot3" => Ok(FractalKind::Multibrot3),
+            "burning_ship" => Ok(FractalKind::BurningShip),
+            _ => Err(format!(
+                "unknown fractal kind '{}' (expected mandelbrot, mandelbrot3 or burning_ship)",
+                s
+            )),
+        }
+    }
+}
+
+/// Run the escape-time recurrence for `kind` at `c`.
+///
+/// On escape, returns the iteration count together with the final
+/// `norm_sqr()`, so callers can turn the discrete count into a smooth
+/// (fractional) value instead of banding at integer iterations.
+fn escape_time(c: Complex<f64>, limit: u32, kind: FractalKind) -> Option<(u32, f64)> {
     let mut z = Complex { re: 0.0, im: 0.0 };
     for i in 0..limit {
-        z = z * z + c;
+        z = match kind {
+            FractalKind::Mandelbrot => z * z + c,
+            FractalKind::Multibrot3 => z * z * z + c,
+            FractalKind::BurningShip => {
+                let folded = Complex {
+                    re: z.re.abs(),
+                    im: z.im.abs(),
+                };
+                folded * folded + c
+            }
+        };
         if z.norm_sqr() > 4.0 {
-            return Some(i);
+            return Some((i, z.norm_sqr()));
         }
     }
 
     None
 }
 
+/// Turn a discrete escape count and the final `|z|^2` into a continuous
+/// iteration count, eliminating the banding a plain integer count shows.
+///
+/// `norm_sqr` is guarded against `|z| <= 1.0`, where `ln(ln(|z|))` is
+/// undefined (or blows up), by falling back to the integer count.
+fn smooth_iteration_count(count: u32, norm_sqr: f64) -> f64 {
+    let norm = norm_sqr.sqrt();
+    if norm <= 1.0 {
+        return count as f64;
+    }
+    count as f64 + 1.0 - (norm.ln().ln()) / std::f64::consts::LN_2
+}
+
+/// Map a continuous iteration count to an RGB color via an HSV ramp: hue
+/// cycles with `mu` while saturation and value stay high, so the boundary
+/// of the set reads as a smooth gradient instead of discrete bands.
+fn color_for(mu: f64) -> [u8; 3] {
+    let hue = mu * 10.0 % 360.0;
+    hsv_to_rgb8(hue, 0.8, 1.0)
+}
+
+/// Convert an HSV color (`h` in degrees, `s` and `v` in `0.0..=1.0`) to
+/// 8-bit RGB.
+fn hsv_to_rgb8(h: f64, s: f64, v: f64) -> [u8; 3] {
+    let c = v * s;
+    let h_prime = h / 60.0;
+    let x = c * (1.0 - (h_prime % 2.0 - 1.0).abs());
+    let (r1, g1, b1) = match h_prime as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+    [
+        ((r1 + m) * 255.0).round() as u8,
+        ((g1 + m) * 255.0).round() as u8,
+        ((b1 + m) * 255.0).round() as u8,
+    ]
+}
+
+/// Pixel encoding `render` and `write_image` produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorMode {
+    /// One byte per pixel, `255 - count`.
+    Grayscale,
+    /// Three bytes per pixel, smooth escape-time coloring.
+    Rgb,
+}
+
+impl ColorMode {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            ColorMode::Grayscale => 1,
+            ColorMode::Rgb => 3,
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gray" => Ok(ColorMode::Grayscale),
+            "rgb" => Ok(ColorMode::Rgb),
+            _ => Err(format!(
+                "unknown color mode '{}' (expected gray or rgb)",
+                s
+            )),
+        }
+    }
+}
+
 /// Parse a string `s` into a coordinate pair. Like "200x600" or "20.10,0.0"
 /// Secificially, `s` should have a form <left><separator><right>, where <sep> is the
 /// caracter given by  `separator` argument, and <left> and <right>
@@ -71,51 +195,276 @@ fn pixel_to_point(
     }
 }
 
+/// The inverse of `pixel_to_point`: map a point on the complex plane back
+/// to its `(column, row)` pixel, or `None` if it falls outside `bounds`.
+fn point_to_pixel(
+    bounds: (usize, usize),
+    point: Complex<f64>,
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) -> Option<(usize, usize)> {
+    let (width, height) = (
+        lower_right.re - upper_left.re,
+        upper_left.im - lower_right.im,
+    );
+
+    let column = (point.re - upper_left.re) / width * bounds.0 as f64;
+    let row = (upper_left.im - point.im) / height * bounds.1 as f64;
+    if column < 0.0 || row < 0.0 {
+        return None;
+    }
+
+    let (column, row) = (column as usize, row as usize);
+    if column >= bounds.0 || row >= bounds.1 {
+        return None;
+    }
+
+    Some((column, row))
+}
+
+/// Run the plain Mandelbrot recurrence from `c`, returning the full orbit
+/// (one `z` per iteration) if it escapes within `limit` iterations, or
+/// `None` if it never does.
+fn buddhabrot_orbit(c: Complex<f64>, limit: u32) -> Option<Vec<Complex<f64>>> {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    let mut orbit = Vec::with_capacity(limit as usize);
+    for _ in 0..limit {
+        z = z * z + c;
+        orbit.push(z);
+        if z.norm_sqr() > 4.0 {
+            return Some(orbit);
+        }
+    }
+
+    None
+}
+
+/// Sample `samples` random points `c` across (and slightly beyond) the
+/// view rectangle. For every `c` whose orbit escapes between `low` and
+/// `high` iterations, accumulate a hit at every pixel its orbit passes
+/// through. Returns the raw `u32` hit histogram, one entry per pixel.
+///
+/// Each Rayon worker accumulates into its own histogram (`fold`), and the
+/// per-thread histograms are merged at the end (`reduce`), so there's no
+/// contention on a shared counter.
+fn sample_buddhabrot(
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    samples: u32,
+    low: u32,
+    high: u32,
+) -> Vec<u32> {
+    let margin_re = (lower_right.re - upper_left.re).abs() * 0.25;
+    let margin_im = (upper_left.im - lower_right.im).abs() * 0.25;
+    let sample_upper_left = Complex {
+        re: upper_left.re - margin_re,
+        im: upper_left.im + margin_im,
+    };
+    let sample_lower_right = Complex {
+        re: lower_right.re + margin_re,
+        im: lower_right.im - margin_im,
+    };
+
+    (0..samples)
+        .into_par_iter()
+        .fold(
+            || vec![0u32; bounds.0 * bounds.1],
+            |mut histogram, _| {
+                let mut rng = rand::thread_rng();
+                let c = Complex {
+                    re: rng.gen_range(sample_upper_left.re..sample_lower_right.re),
+                    im: rng.gen_range(sample_lower_right.im..sample_upper_left.im),
+                };
+
+                if let Some(orbit) = buddhabrot_orbit(c, high) {
+                    if orbit.len() as u32 >= low {
+                        for z in orbit {
+                            if let Some((column, row)) =
+                                point_to_pixel(bounds, z, upper_left, lower_right)
+                            {
+                                histogram[row * bounds.0 + column] += 1;
+                            }
+                        }
+                    }
+                }
+
+                histogram
+            },
+        )
+        .reduce(
+            || vec![0u32; bounds.0 * bounds.1],
+            |mut a, b| {
+                for (hit, other) in a.iter_mut().zip(b) {
+                    *hit += other;
+                }
+                a
+            },
+        )
+}
+
+/// Normalize a hit histogram into an `0..=255` grayscale buffer, using log
+/// scaling so that the comparatively rare high-hit-count pixels don't
+/// wash out the rest of the image.
+fn normalize_histogram(histogram: &[u32]) -> Vec<u8> {
+    let max = histogram.iter().copied().max().unwrap_or(0);
+    if max == 0 {
+        return vec![0; histogram.len()];
+    }
+
+    let max_log = (max as f64 + 1.0).ln();
+    histogram
+        .iter()
+        .map(|&count| (((count as f64 + 1.0).ln() / max_log) * 255.0).round() as u8)
+        .collect()
+}
+
+/// Compute the grayscale / RGB contribution of a single escape-time
+/// sample, as `f64` components so callers can average several of them
+/// before rounding to a final byte.
+fn sample_value(point: Complex<f64>, kind: FractalKind, mode: ColorMode) -> [f64; 3] {
+    let escaped = escape_time(point, 225, kind);
+    match mode {
+        ColorMode::Grayscale => {
+            let gray = match escaped {
+                None => 0.0,
+                Some((count, _)) => 255.0 - count as f64,
+            };
+            [gray, gray, gray]
+        }
+        ColorMode::Rgb => match escaped {
+            None => [0.0, 0.0, 0.0],
+            Some((count, norm_sqr)) => {
+                let rgb = color_for(smooth_iteration_count(count, norm_sqr));
+                [rgb[0] as f64, rgb[1] as f64, rgb[2] as f64]
+            }
+        },
+    }
+}
+
+/// Render `bounds.0 x bounds.1` pixels of `kind` into `pixels`.
+///
+/// Each pixel is evaluated on a `supersample x supersample` grid of
+/// sub-samples spanning its complex-plane cell, and the resulting values
+/// are averaged, antialiasing the harsh edges a single sample per pixel
+/// produces. `supersample == 1` reduces to plain one-sample-per-pixel
+/// rendering.
 fn render(
     pixels: &mut [u8],
     bounds: (usize, usize),
     upper_left: Complex<f64>,
     lower_right: Complex<f64>,
+    kind: FractalKind,
+    mode: ColorMode,
+    supersample: usize,
 ) {
-    assert!(pixels.len() == bounds.0 * bounds.1);
+    let bpp = mode.bytes_per_pixel();
+    assert!(pixels.len() == bounds.0 * bounds.1 * bpp);
+    assert!(supersample >= 1);
+
+    let sub_bounds = (bounds.0 * supersample, bounds.1 * supersample);
+    let samples = (supersample * supersample) as f64;
 
     for row in 0..bounds.1 {
         for column in 0..bounds.0 {
-            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+            let offset = (row * bounds.0 + column) * bpp;
+            let mut sum = [0.0; 3];
+
+            for sub_row in 0..supersample {
+                for sub_col in 0..supersample {
+                    let sub_pixel = (
+                        column * supersample + sub_col,
+                        row * supersample + sub_row,
+                    );
+                    let point = pixel_to_point(sub_bounds, sub_pixel, upper_left, lower_right);
+                    let value = sample_value(point, kind, mode);
+                    for i in 0..3 {
+                        sum[i] += value[i];
+                    }
+                }
+            }
 
-            pixels[row * bounds.0 + column] = match escape_time(point, 225) {
-                None => 0,
-                Some(count) => 255 - count as u8,
+            match mode {
+                ColorMode::Grayscale => pixels[offset] = (sum[0] / samples).round() as u8,
+                ColorMode::Rgb => {
+                    for i in 0..3 {
+                        pixels[offset + i] = (sum[i] / samples).round() as u8;
+                    }
+                }
             }
         }
     }
 }
 
 use std::error::Error;
-/// Write the buffer `pixels`, whose dimensions are given by `bounds`, to the file named `filename.
+
+/// Write the buffer `pixels`, whose dimensions are given by `bounds`, to
+/// the file named `filename`, dispatching on its extension: `.pgm`/`.ppm`
+/// go through the NetPBM writer, everything else is written as PNG.
 fn write_image(
     filename: &str,
     pixels: &[u8],
     bounds: (usize, usize),
+    mode: ColorMode,
+) -> Result<(), Box<dyn Error>> {
+    match filename.rsplit('.').next() {
+        Some("pgm") | Some("ppm") => write_pnm(filename, pixels, bounds, mode),
+        _ => write_png(filename, pixels, bounds, mode),
+    }
+}
+
+fn write_png(
+    filename: &str,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    mode: ColorMode,
 ) -> Result<(), Box<dyn Error>> {
     let output = File::create(filename)?;
 
+    let color_type = match mode {
+        ColorMode::Grayscale => ColorType::L8,
+        ColorMode::Rgb => ColorType::Rgb8,
+    };
+
     let encoder = PngEncoder::new(output);
-    encoder.write_image(&pixels, bounds.0 as u32, bounds.1 as u32, ColorType::L8)?;
+    encoder.write_image(&pixels, bounds.0 as u32, bounds.1 as u32, color_type)?;
 
     Ok(())
 }
 
-use std::io::Write;
+/// Write `pixels` as a binary NetPBM file: PGM (`P5`) for grayscale,
+/// PPM (`P6`) for RGB. The header is `magic\nwidth height\nmaxval\n`,
+/// followed by the raw pixel bytes.
+fn write_pnm(
+    filename: &str,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    mode: ColorMode,
+) -> Result<(), Box<dyn Error>> {
+    let mut output = File::create(filename)?;
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let magic = match mode {
+        ColorMode::Grayscale => "P5",
+        ColorMode::Rgb => "P6",
+    };
+
+    writeln!(output, "{}\n{} {}\n255", magic, bounds.0, bounds.1)?;
+    output.write_all(pixels)?;
+
+    Ok(())
+}
+
+use std::io::Write;
 
-    if args.len() != 5 {
+fn run_render(args: &[String]) {
+    if args.len() != 7 && args.len() != 8 {
         writeln!(
             std::io::stderr(),
-            "Usage: mandelbrot FILE PIXELS UPPERLEFT LOWERRIGHT\n
-        Example: {} mandel.png 100x750 -1.20,0.35 -1,0.20",
+            "Usage: mandelbrot FILE PIXELS UPPERLEFT LOWERRIGHT FRACTAL COLOR [SUPERSAMPLE]\n
+        Example: {} mandel.png 100x750 -1.20,0.35 -1,0.20 mandelbrot rgb 2\n
+        FRACTAL is one of: mandelbrot, mandelbrot3, burning_ship\n
+        COLOR is one of: gray, rgb\n
+        SUPERSAMPLE is the N x N sub-sample grid per pixel, defaults to 1",
             args[0]
         )
         .unwrap();
@@ -125,31 +474,75 @@ fn main() {
     let bounds = parse_pair(&args[2], 'x').expect("error parsing image dimensions");
     let upper_left = parse_complex(&args[3]).expect("error parsing upper left conner point");
     let lower_right = parse_complex(&args[4]).expect("error parsing lower right conner point");
+    let kind = FractalKind::from_str(&args[5]).expect("error parsing fractal kind");
+    let mode = ColorMode::from_str(&args[6]).expect("error parsing color mode");
+    let supersample: usize = match args.get(7) {
+        Some(n) => n.parse().expect("error parsing supersample factor"),
+        None => 1,
+    };
     let filename = &args[1];
 
-    let mut pixels = vec![0; bounds.0 * bounds.1];
-
-    let threads = 8;
-    let rows_per_band = bounds.1 / threads + 1;
-    {
-        let bands: Vec<&mut [u8]> = pixels.chunks_mut(rows_per_band * bounds.0).collect();
-        crossbeam::scope(|scope| {
-            for (i, band) in bands.into_iter().enumerate() {
-                let top = rows_per_band * i;
-                let height = band.len() / bounds.0;
-                let band_bounds = (bounds.0, height);
-                let band_upper_left = pixel_to_point(bounds, (0, top), upper_left, lower_right);
-                let band_lower_right =
-                    pixel_to_point(bounds, (bounds.0, top + height), upper_left, lower_right);
-                scope.spawn(move |_| {
-                    render(band, band_bounds, band_upper_left, band_lower_right);
-                });
-            }
-        })
-        .expect("Failed to start thread!");
-    };
+    let bpp = mode.bytes_per_pixel();
+    let mut pixels = vec![0; bounds.0 * bounds.1 * bpp];
+
+    // One chunk per row: interior pixels run to the full iteration limit
+    // while exterior ones bail out early, so per-row work varies wildly.
+    // Rayon's work-stealing scheduler balances that instead of a fixed
+    // number of equal-sized bands.
+    pixels
+        .par_chunks_mut(bounds.0 * bpp)
+        .enumerate()
+        .for_each(|(row, band)| {
+            let band_bounds = (bounds.0, 1);
+            let band_upper_left = pixel_to_point(bounds, (0, row), upper_left, lower_right);
+            let band_lower_right = pixel_to_point(bounds, (bounds.0, row + 1), upper_left, lower_right);
+            render(
+                band,
+                band_bounds,
+                band_upper_left,
+                band_lower_right,
+                kind,
+                mode,
+                supersample,
+            );
+        });
+
+    write_image(filename, &pixels, bounds, mode).expect("error writing image file");
+}
+
+fn run_buddhabrot(args: &[String]) {
+    if args.len() != 9 {
+        writeln!(
+            std::io::stderr(),
+            "Usage: mandelbrot buddhabrot FILE PIXELS UPPERLEFT LOWERRIGHT SAMPLES LOW HIGH\n
+        Example: {} buddhabrot buddha.png 600x600 -2,1.2 1,-1.2 5000000 20 2000",
+            args[0]
+        )
+        .unwrap();
+        std::process::exit(1);
+    }
+
+    let filename = &args[2];
+    let bounds = parse_pair(&args[3], 'x').expect("error parsing image dimensions");
+    let upper_left = parse_complex(&args[4]).expect("error parsing upper left conner point");
+    let lower_right = parse_complex(&args[5]).expect("error parsing lower right conner point");
+    let samples: u32 = args[6].parse().expect("error parsing sample count");
+    let low: u32 = args[7].parse().expect("error parsing low iteration threshold");
+    let high: u32 = args[8].parse().expect("error parsing high iteration threshold");
+
+    let histogram = sample_buddhabrot(bounds, upper_left, lower_right, samples, low, high);
+    let pixels = normalize_histogram(&histogram);
+
+    write_image(filename, &pixels, bounds, ColorMode::Grayscale).expect("error writing image file");
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
 
-    write_image(filename, &pixels, bounds).expect("error writing PNG file");
+    match args.get(1).map(String::as_str) {
+        Some("buddhabrot") => run_buddhabrot(&args),
+        _ => run_render(&args),
+    }
 }
 
 #[test]
@@ -176,6 +569,14 @@ fn test_parse_complex() {
     assert_eq!(parse_complex(",0.1"), None);
 }
 
+#[test]
+fn test_fractal_kind_from_str() {
+    assert_eq!(FractalKind::from_str("mandelbrot"), Ok(FractalKind::Mandelbrot));
+    assert_eq!(FractalKind::from_str("mandelbrot3"), Ok(FractalKind::Multibrot3));
+    assert_eq!(FractalKind::from_str("burning_ship"), Ok(FractalKind::BurningShip));
+    assert!(FractalKind::from_str("toto").is_err());
+}
+
 #[test]
 fn test_pixel_to_point() {
     assert_eq!(